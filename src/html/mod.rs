@@ -31,13 +31,19 @@ impl HtmlExporter {
             .get_handles()
             .into_iter()
             .map(|(desc, handle)| {
+                let value = match desc.kind() {
+                    MetricKind::Counter => handle.read_counter().into(),
+                    MetricKind::Gauge => handle.read_gauge().into(),
+                    MetricKind::Histogram => handle.read_histogram().into(),
+                };
+                let metadata = self.recorder.metadata(&desc);
                 (
                     desc.key().name().to_string(),
-                    match desc.kind() {
-                        MetricKind::Counter => handle.read_counter().into(),
-                        MetricKind::Gauge => handle.read_gauge().into(),
-                        MetricKind::Histogram => handle.read_histogram().into(),
-                    },
+                    json!({
+                        "value": value,
+                        "unit": metadata.unit.map(|unit| unit.as_str()),
+                        "description": metadata.description,
+                    }),
                 )
             })
             .collect();
@@ -2,6 +2,8 @@
 
 use std::io;
 use std::net::{Ipv6Addr, SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::mpsc;
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
@@ -10,18 +12,37 @@ use metrics::{self, SetRecorderError};
 mod html;
 mod recorder;
 mod statsd;
+mod transport;
 
 use crate::recorder::PlainRecorder;
 use crate::statsd::StatsdExporter;
+use crate::transport::Transport;
 
 pub use html::HtmlExporter;
 
+/// Which transport a [`MetricsBuilder`] will hand to the exporter. Defaults
+/// to UDP; overridden by calling [`MetricsBuilder::tcp`] or
+/// [`MetricsBuilder::unix_datagram`].
+#[derive(Debug, Clone)]
+enum TransportConfig {
+    Udp,
+    Tcp(SocketAddr),
+    UnixDatagram(PathBuf),
+}
+
 #[derive(Debug, Clone)]
 pub struct MetricsBuilder {
     statsd: bool,
     local_addr: SocketAddr,
     peer_addr: SocketAddr,
+    transport: TransportConfig,
     interval: Duration,
+    dogstatsd: bool,
+    constant_tags: Vec<(String, String)>,
+    prefix: Option<String>,
+    histogram_summary: Option<Vec<f64>>,
+    max_packet_size: usize,
+    sample_rate: f64,
 }
 
 #[derive(Debug)]
@@ -36,7 +57,14 @@ impl MetricsBuilder {
             statsd: true,
             local_addr: SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
             peer_addr: SocketAddr::from((Ipv6Addr::LOCALHOST, 8125)),
+            transport: TransportConfig::Udp,
             interval: Duration::from_secs(5),
+            dogstatsd: false,
+            constant_tags: Vec::new(),
+            prefix: None,
+            histogram_summary: None,
+            max_packet_size: 512,
+            sample_rate: 1.0,
         }
     }
 
@@ -45,6 +73,51 @@ impl MetricsBuilder {
         self
     }
 
+    /// Emit DogStatsD-flavoured lines, rendering a metric's labels as a
+    /// trailing `|#key:val,...` tag suffix.
+    ///
+    /// Plain statsd servers reject the `|#` suffix, so this defaults to off.
+    pub fn dogstatsd(&mut self, enabled: bool) -> &mut Self {
+        self.dogstatsd = enabled;
+        self
+    }
+
+    /// Tags merged into every metric line, in addition to any labels carried
+    /// by the individual `Key`. Only takes effect in DogStatsD mode.
+    pub fn constant_tags(&mut self, tags: Vec<(String, String)>) -> &mut Self {
+        self.constant_tags = tags;
+        self
+    }
+
+    /// Prefix prepended to every metric name with a `.` separator.
+    pub fn prefix(&mut self, prefix: String) -> &mut Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    /// Compute histogram summary statistics (count/min/max/mean plus the
+    /// given quantiles) locally and emit them as gauges instead of flooding
+    /// the downstream statsd server with raw `|ms` samples.
+    pub fn histogram_summary(&mut self, quantiles: Vec<f64>) -> &mut Self {
+        self.histogram_summary = Some(quantiles);
+        self
+    }
+
+    /// Largest UDP datagram a single send may occupy (default 512 bytes).
+    /// Metric lines are never split across datagrams.
+    pub fn max_packet_size(&mut self, size: usize) -> &mut Self {
+        self.max_packet_size = size;
+        self
+    }
+
+    /// Throttle counter/timer volume sent to the statsd server by the given
+    /// rate (0.0-1.0), appending the `|@<rate>` sample-rate suffix so the
+    /// server reconstructs correct aggregates. Defaults to `1.0` (disabled).
+    pub fn sample_rate(&mut self, rate: f64) -> &mut Self {
+        self.sample_rate = rate;
+        self
+    }
+
     pub fn local_addr(&mut self, addr: SocketAddr) -> &mut Self {
         self.local_addr = addr;
         self
@@ -55,18 +128,47 @@ impl MetricsBuilder {
         self
     }
 
+    /// Send metrics over a TCP connection instead of UDP. Useful when
+    /// metric lines must not be silently dropped under load. If a send
+    /// fails, the error is logged, that batch is lost, and the next send
+    /// opens a new connection.
+    pub fn tcp(&mut self, addr: SocketAddr) -> &mut Self {
+        self.transport = TransportConfig::Tcp(addr);
+        self
+    }
+
+    /// Send metrics over a Unix datagram socket instead of UDP, avoiding
+    /// loopback overhead when the statsd agent runs on the same host.
+    pub fn unix_datagram(&mut self, path: PathBuf) -> &mut Self {
+        self.transport = TransportConfig::UnixDatagram(path);
+        self
+    }
+
     pub fn interval(&mut self, duration: Duration) -> &mut Self {
         self.interval = duration;
         self
     }
 
     fn create_exporter(&self, recorder: PlainRecorder) -> Result<StatsdExporter, io::Error> {
-        Ok(StatsdExporter::new(
-            UdpSocket::bind(self.local_addr)?,
-            self.peer_addr,
-            self.interval,
-            recorder,
-        ))
+        let transport = match &self.transport {
+            TransportConfig::Udp => {
+                Transport::udp(UdpSocket::bind(self.local_addr)?, self.peer_addr)
+            }
+            TransportConfig::Tcp(addr) => Transport::tcp(*addr),
+            TransportConfig::UnixDatagram(path) => Transport::unix_datagram(path.clone())?,
+        };
+        let mut exporter = StatsdExporter::new(transport, self.interval, recorder);
+        exporter.dogstatsd(self.dogstatsd);
+        exporter.constant_tags(self.constant_tags.clone());
+        if let Some(prefix) = &self.prefix {
+            exporter.prefix(prefix.clone());
+        }
+        if let Some(quantiles) = &self.histogram_summary {
+            exporter.histogram_summary(quantiles.clone());
+        }
+        exporter.max_packet_size(self.max_packet_size);
+        exporter.sample_rate(self.sample_rate);
+        Ok(exporter)
     }
 
     pub fn install(&self) -> Result<MetricsCollector, InstallError> {
@@ -75,20 +177,17 @@ impl MetricsBuilder {
             .create_exporter(recorder.clone())
             .map_err(InstallError::Build)?;
         metrics::set_boxed_recorder(Box::new(recorder.clone())).map_err(InstallError::Install)?;
-        let handle = if self.statsd {
-            let handle = thread::spawn(move || match exporter.run() {
-                Ok(()) => (),
-                Err(e) => {
-                    log::error!("Statsd exporter failed: {}", e);
-                }
-            });
-            Some(handle)
+        let (statsd_handle, shutdown_tx) = if self.statsd {
+            let (shutdown_tx, shutdown_rx) = mpsc::channel();
+            let handle = thread::spawn(move || exporter.run(shutdown_rx));
+            (Some(handle), Some(shutdown_tx))
         } else {
-            None
+            (None, None)
         };
         Ok(MetricsCollector {
             recorder,
-            statsd_handle: handle,
+            statsd_handle,
+            shutdown_tx,
         })
     }
 }
@@ -103,6 +202,7 @@ impl Default for MetricsBuilder {
 pub struct MetricsCollector {
     recorder: PlainRecorder,
     statsd_handle: Option<JoinHandle<()>>,
+    shutdown_tx: Option<mpsc::Sender<()>>,
 }
 
 impl MetricsCollector {
@@ -117,4 +217,18 @@ impl MetricsCollector {
     pub fn recorder(&self) -> impl metrics::Recorder {
         self.recorder.clone()
     }
+
+    /// Stop the statsd exporter thread, flushing one last batch of metrics
+    /// before it exits.
+    ///
+    /// Blocks until the exporter thread has sent the final batch and
+    /// terminated. A no-op if `statsd` was disabled on the builder.
+    pub fn shutdown(mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        if let Some(handle) = self.statsd_handle.take() {
+            let _ = handle.join();
+        }
+    }
 }
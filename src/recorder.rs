@@ -1,17 +1,27 @@
 //! Plain recorder.
 
+use std::collections::HashMap;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use metrics::{Identifier, Key, Recorder};
+use metrics::{Identifier, Key, Recorder, Unit};
 use metrics_util::{CompositeKey, Handle, MetricKind, Registry};
 
+/// The unit and description a metric was registered with, kept alongside
+/// the registry since `Registry<K, Handle>` only stores the handle itself.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Metadata {
+    pub(crate) unit: Option<Unit>,
+    pub(crate) description: Option<&'static str>,
+}
+
 /// A simple recorder doing nothing fancy but record the plain values.
 ///
 /// Cloning this is cheap since the clones will refer to the same metrics storage.
 #[derive(Clone)]
 pub(crate) struct PlainRecorder {
     pub(crate) registry: Arc<Registry<CompositeKey, Handle>>,
+    metadata: Arc<Mutex<HashMap<CompositeKey, Metadata>>>,
 }
 
 impl fmt::Debug for PlainRecorder {
@@ -24,30 +34,58 @@ impl PlainRecorder {
     pub(crate) fn new() -> Self {
         Self {
             registry: Arc::new(Registry::new()),
+            metadata: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// The unit/description a metric was registered with, if any.
+    pub(crate) fn metadata(&self, key: &CompositeKey) -> Metadata {
+        self.metadata.lock().unwrap().get(key).cloned().unwrap_or_default()
+    }
+
+    fn store_metadata(&self, key: CompositeKey, unit: Option<Unit>, description: Option<&'static str>) {
+        self.metadata
+            .lock()
+            .unwrap()
+            .insert(key, Metadata { unit, description });
+    }
 }
 
 impl Recorder for PlainRecorder {
-    fn register_counter(&self, key: Key, _description: Option<&'static str>) -> Identifier {
+    fn register_counter(
+        &self,
+        key: Key,
+        unit: Option<Unit>,
+        description: Option<&'static str>,
+    ) -> Identifier {
+        let composite_key = CompositeKey::new(MetricKind::Counter, key);
+        self.store_metadata(composite_key.clone(), unit, description);
         self.registry
-            .get_or_create_identifier(CompositeKey::new(MetricKind::Counter, key), |_key| {
-                Handle::counter()
-            })
+            .get_or_create_identifier(composite_key, |_key| Handle::counter())
     }
 
-    fn register_gauge(&self, key: Key, _description: Option<&'static str>) -> Identifier {
+    fn register_gauge(
+        &self,
+        key: Key,
+        unit: Option<Unit>,
+        description: Option<&'static str>,
+    ) -> Identifier {
+        let composite_key = CompositeKey::new(MetricKind::Gauge, key);
+        self.store_metadata(composite_key.clone(), unit, description);
         self.registry
-            .get_or_create_identifier(CompositeKey::new(MetricKind::Gauge, key), |_key| {
-                Handle::gauge()
-            })
+            .get_or_create_identifier(composite_key, |_key| Handle::gauge())
     }
 
-    fn register_histogram(&self, key: Key, _description: Option<&'static str>) -> Identifier {
+    fn register_histogram(
+        &self,
+        key: Key,
+        unit: Option<Unit>,
+        description: Option<&'static str>,
+    ) -> Identifier {
+        let composite_key = CompositeKey::new(MetricKind::Histogram, key);
+        self.store_metadata(composite_key.clone(), unit, description);
         self.registry
-            .get_or_create_identifier(CompositeKey::new(MetricKind::Histogram, key), |_key| {
-                Handle::histogram()
-            })
+            .get_or_create_identifier(composite_key, |_key| Handle::histogram())
     }
 
     fn increment_counter(&self, id: Identifier, value: u64) {
@@ -74,9 +112,9 @@ mod tests {
     fn test_counter() {
         let rec = PlainRecorder::new();
 
-        let c0 = rec.register_counter(Key::from_name("spam.ham"), None);
-        let c1 = rec.register_counter(Key::from_name("spam.eggs"), None);
-        let c2 = rec.register_counter(Key::from_name("spam.ham"), None);
+        let c0 = rec.register_counter(Key::from_name("spam.ham"), None, None);
+        let c1 = rec.register_counter(Key::from_name("spam.eggs"), None, None);
+        let c2 = rec.register_counter(Key::from_name("spam.ham"), None, None);
         assert_eq!(c0, c2);
         assert_ne!(c0, c1);
 
@@ -94,9 +132,9 @@ mod tests {
     fn test_register_gauge() {
         let rec = PlainRecorder::new();
 
-        let g0 = rec.register_gauge(Key::from_name("spam.ham"), None);
-        let g1 = rec.register_gauge(Key::from_name("spam.eggs"), None);
-        let g2 = rec.register_gauge(Key::from_name("spam.ham"), None);
+        let g0 = rec.register_gauge(Key::from_name("spam.ham"), None, None);
+        let g1 = rec.register_gauge(Key::from_name("spam.eggs"), None, None);
+        let g2 = rec.register_gauge(Key::from_name("spam.ham"), None, None);
         assert_eq!(g0, g2);
         assert_ne!(g0, g1);
 
@@ -109,4 +147,17 @@ mod tests {
             assert_eq!(handle.read_gauge(), 3.0);
         });
     }
+
+    #[test]
+    fn test_metadata() {
+        let rec = PlainRecorder::new();
+
+        let key = Key::from_name("spam.ham");
+        rec.register_counter(key.clone(), Some(Unit::Milliseconds), Some("how much spam"));
+
+        let composite_key = CompositeKey::new(MetricKind::Counter, key);
+        let metadata = rec.metadata(&composite_key);
+        assert_eq!(metadata.unit, Some(Unit::Milliseconds));
+        assert_eq!(metadata.description, Some("how much spam"));
+    }
 }
@@ -1,75 +1,334 @@
+use std::collections::HashMap;
 use std::io;
-use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc;
 use std::time::Duration;
 
-use bytes::{Buf, BufMut, Bytes, BytesMut};
-use metrics_util::MetricKind;
+use bytes::{BufMut, Bytes, BytesMut};
+use metrics::{Key, Unit};
+use metrics_util::{CompositeKey, Handle, MetricKind};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use crate::recorder::PlainRecorder;
+use crate::transport::Transport;
+
+const DEFAULT_MAX_PACKET_SIZE: usize = 512;
 
 #[derive(Debug)]
 pub struct StatsdExporter {
-    local_socket: UdpSocket,
-    peer_addr: SocketAddr,
+    transport: Transport,
     interval: Duration,
     recorder: PlainRecorder,
+    dogstatsd: bool,
+    constant_tags: Vec<(String, String)>,
+    prefix: Option<String>,
+    histogram_summary: Option<Vec<f64>>,
+    max_packet_size: usize,
+    sample_rate: f64,
+    rng: StdRng,
+    buf: BytesMut,
+    /// Last value exported per counter/gauge, keyed by the same
+    /// `CompositeKey` the registry already uses. Lets `export` skip metrics
+    /// whose value hasn't changed since the previous interval.
+    last_values: HashMap<CompositeKey, f64>,
+    /// Number of histogram samples already exported per histogram, keyed by
+    /// the same `CompositeKey` the registry uses. `Handle::read_histogram`
+    /// is a non-destructive, cumulative read (like `read_counter`), so this
+    /// cursor lets `export_histogram` only process samples recorded since
+    /// the previous interval instead of re-emitting the whole history.
+    histogram_cursor: HashMap<CompositeKey, usize>,
 }
 
 impl StatsdExporter {
-    pub(crate) fn new(
-        local_socket: UdpSocket,
-        peer_addr: SocketAddr,
-        interval: Duration,
-        recorder: PlainRecorder,
-    ) -> Self {
+    pub(crate) fn new(transport: Transport, interval: Duration, recorder: PlainRecorder) -> Self {
         Self {
-            local_socket,
-            peer_addr,
+            transport,
             interval,
             recorder,
+            dogstatsd: false,
+            constant_tags: Vec::new(),
+            prefix: None,
+            histogram_summary: None,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            sample_rate: 1.0,
+            rng: StdRng::from_entropy(),
+            buf: BytesMut::with_capacity(DEFAULT_MAX_PACKET_SIZE),
+            last_values: HashMap::new(),
+            histogram_cursor: HashMap::new(),
         }
     }
 
-    fn export(&self) -> Bytes {
-        // TODO: re-use the allocated buffer
-        // TODO: chunk this into 512 byte buffers
-        // TODO: re-set the data sent, only send updates
-        let mut buf = BytesMut::with_capacity(512);
-        for (desc, handle) in self.recorder.registry.get_handles() {
-            match desc.kind() {
-                MetricKind::Counter | MetricKind::Gauge => (),
-                _ => continue,
+    /// Toggle DogStatsD-flavoured output (adds the `|#key:val,...` tag suffix).
+    ///
+    /// Plain statsd servers reject the `|#` suffix, so this defaults to off.
+    pub(crate) fn dogstatsd(&mut self, enabled: bool) -> &mut Self {
+        self.dogstatsd = enabled;
+        self
+    }
+
+    /// Tags merged into every emitted metric line when DogStatsD mode is on.
+    pub(crate) fn constant_tags(&mut self, tags: Vec<(String, String)>) -> &mut Self {
+        self.constant_tags = tags;
+        self
+    }
+
+    /// Prefix prepended to every metric name, joined with a `.`.
+    pub(crate) fn prefix(&mut self, prefix: String) -> &mut Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    /// Compute summary statistics (count/min/max/mean/quantiles) for
+    /// histograms locally instead of emitting one raw timing line per
+    /// sample.
+    pub(crate) fn histogram_summary(&mut self, quantiles: Vec<f64>) -> &mut Self {
+        self.histogram_summary = Some(quantiles);
+        self
+    }
+
+    /// Largest UDP datagram a single send may occupy. Metric lines are
+    /// never split across datagrams, so a single line longer than this is
+    /// still sent whole, as its own oversized datagram.
+    pub(crate) fn max_packet_size(&mut self, size: usize) -> &mut Self {
+        self.max_packet_size = size;
+        self
+    }
+
+    /// Fraction of counter/timer data actually sent to the statsd server,
+    /// appended as a `|@<rate>` suffix so the server can reconstruct the
+    /// true aggregate. Counter deltas are scaled down by this rate; raw
+    /// timing samples are instead randomly dropped with probability
+    /// `1 - rate`. Has no effect at the default of `1.0`.
+    pub(crate) fn sample_rate(&mut self, rate: f64) -> &mut Self {
+        self.sample_rate = rate;
+        self
+    }
+
+    /// The `|@<rate>` suffix to append when sampling is enabled, or an
+    /// empty string otherwise.
+    fn rate_suffix(&self) -> String {
+        if self.sample_rate < 1.0 {
+            format!("|@{}", self.sample_rate)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Decide whether to keep the next timing sample, consuming `self.rng`
+    /// only when sampling is actually enabled.
+    fn should_emit_sample(&mut self) -> bool {
+        self.sample_rate >= 1.0 || self.rng.gen::<f64>() < self.sample_rate
+    }
+
+    /// Render the DogStatsD tag suffix (without the leading `|#`) for `key`,
+    /// combining the configured constant tags with the key's own labels.
+    fn render_tags(&self, key: &Key) -> String {
+        let mut tags: Vec<String> = self
+            .constant_tags
+            .iter()
+            .map(|(k, v)| format!("{}:{}", k, v))
+            .collect();
+        tags.extend(
+            key.labels()
+                .map(|label| format!("{}:{}", label.key(), label.value())),
+        );
+        tags.join(",")
+    }
+
+    /// Write a single `name:value|type` line into the exporter's buffer,
+    /// followed by the tag suffix (when DogStatsD mode is on and `tags` is
+    /// non-empty) and a newline.
+    fn write_line(&mut self, name: &str, value_and_type: &str, tags: &str) {
+        self.buf.put_slice(name.as_bytes());
+        self.buf.put_slice(b":");
+        self.buf.put_slice(value_and_type.as_bytes());
+        if self.dogstatsd && !tags.is_empty() {
+            self.buf.put_slice(b"|#");
+            self.buf.put_slice(tags.as_bytes());
+        }
+        self.buf.put_slice(b"\n");
+    }
+
+    /// Emit either raw timing lines or locally-computed summary gauges for a
+    /// histogram's new samples, depending on `histogram_summary`.
+    ///
+    /// `read_histogram` is a non-destructive, cumulative read, so only the
+    /// samples recorded since `desc`'s last export (tracked via
+    /// `histogram_cursor`) are processed here.
+    fn export_histogram(
+        &mut self,
+        desc: &CompositeKey,
+        metric_name: &str,
+        handle: &Handle,
+        tags: &str,
+        unit: Option<Unit>,
+    ) {
+        let all_samples = handle.read_histogram();
+        let cursor = self.histogram_cursor.get(desc).copied().unwrap_or(0);
+        if all_samples.len() <= cursor {
+            return;
+        }
+        let samples = all_samples[cursor..].to_vec();
+        self.histogram_cursor.insert(desc.clone(), all_samples.len());
+        // Cloned up front: holding a `&self.histogram_summary` borrow across
+        // the `self.write_line` calls below would conflict with `&mut self`.
+        match self.histogram_summary.clone() {
+            Some(quantiles) => {
+                let count = samples.len();
+                let min = *samples.iter().min().unwrap();
+                let max = *samples.iter().max().unwrap();
+                let mean = samples.iter().sum::<u64>() as f64 / count as f64;
+                self.write_line(&format!("{}.count", metric_name), &format!("{}|g", count), tags);
+                self.write_line(&format!("{}.min", metric_name), &format!("{}|g", min), tags);
+                self.write_line(&format!("{}.max", metric_name), &format!("{}|g", max), tags);
+                self.write_line(&format!("{}.mean", metric_name), &format!("{}|g", mean), tags);
+
+                let mut sorted = samples;
+                sorted.sort_unstable();
+                for q in quantiles {
+                    let idx = (((sorted.len() - 1) as f64) * q)
+                        .round()
+                        .clamp(0.0, (sorted.len() - 1) as f64) as usize;
+                    let value = sorted[idx];
+                    let name = format!("{}.p{}", metric_name, (q * 100.0).round() as u32);
+                    self.write_line(&name, &format!("{}|g", value), tags);
+                }
+            }
+            None => {
+                // DogStatsD has a single histogram type regardless of unit;
+                // plain statsd distinguishes a `|c` count-typed histogram
+                // from a `|ms` duration-typed one.
+                let type_suffix = if self.dogstatsd {
+                    "h"
+                } else if unit == Some(Unit::Count) || unit == Some(Unit::CountPerSecond) {
+                    "c"
+                } else {
+                    "ms"
+                };
+                let rate_suffix = self.rate_suffix();
+                for sample in samples {
+                    if !self.should_emit_sample() {
+                        continue;
+                    }
+                    self.write_line(
+                        metric_name,
+                        &format!("{}|{}{}", sample, type_suffix, rate_suffix),
+                        tags,
+                    );
+                }
             }
-            let metric_name = desc.key().name();
-            buf.put_slice(metric_name.as_bytes());
-            buf.put_slice(":".as_bytes());
+        }
+    }
+
+    /// Build the next batch of metric lines into the reusable buffer and
+    /// hand ownership of the written bytes back as `Bytes`, leaving the
+    /// buffer's allocation in place for the next call.
+    fn export(&mut self) -> Bytes {
+        for (desc, handle) in self.recorder.registry.get_handles() {
+            let metric_name = match &self.prefix {
+                Some(prefix) => format!("{}.{}", prefix, desc.key().name()),
+                None => desc.key().name().to_string(),
+            };
+            let tags = if self.dogstatsd {
+                self.render_tags(desc.key())
+            } else {
+                String::new()
+            };
             match desc.kind() {
                 MetricKind::Counter => {
-                    buf.put_slice(format!("{}|c", handle.read_counter()).as_bytes());
+                    let total = handle.read_counter();
+                    let unchanged =
+                        matches!(self.last_values.get(&desc), Some(&prev) if prev as u64 == total);
+                    if unchanged {
+                        continue;
+                    }
+                    let prev = self.last_values.get(&desc).copied().unwrap_or(0.0) as u64;
+                    let delta = total.saturating_sub(prev);
+                    let scaled = if self.sample_rate < 1.0 {
+                        (delta as f64 * self.sample_rate).round() as u64
+                    } else {
+                        delta
+                    };
+                    // A nonzero delta that rounds down to zero under
+                    // sampling isn't reported this interval, but `prev` is
+                    // deliberately left unadvanced so the unreported amount
+                    // carries into the next interval's delta instead of
+                    // being silently dropped. (A genuinely zero delta is
+                    // already filtered above, except on the metric's first
+                    // export, which should still report its baseline value.)
+                    if delta > 0 && scaled == 0 {
+                        continue;
+                    }
+                    self.last_values.insert(desc.clone(), total as f64);
+                    let rate_suffix = self.rate_suffix();
+                    self.write_line(&metric_name, &format!("{}|c{}", scaled, rate_suffix), &tags);
                 }
                 MetricKind::Gauge => {
-                    buf.put_slice(format!("{}|g", handle.read_gauge()).as_bytes());
+                    let value = handle.read_gauge();
+                    let unchanged = matches!(self.last_values.get(&desc), Some(&prev) if prev == value);
+                    if unchanged {
+                        continue;
+                    }
+                    self.last_values.insert(desc.clone(), value);
+                    self.write_line(&metric_name, &format!("{}|g", value), &tags);
+                }
+                MetricKind::Histogram => {
+                    let unit = self.recorder.metadata(&desc).unit;
+                    self.export_histogram(&desc, &metric_name, &handle, &tags, unit);
                 }
-                _ => continue,
             }
-            buf.put_slice("\n".as_bytes());
         }
-        buf.freeze()
+        self.buf.split().freeze()
     }
 
-    fn send(&self) -> io::Result<()> {
-        let mut data = self.export();
-        while data.has_remaining() {
-            let count = self.local_socket.send_to(data.bytes(), &self.peer_addr)?;
-            data.advance(count);
+    /// Send the current batch of metric lines.
+    ///
+    /// On a stream transport (TCP) the whole batch goes out as one write,
+    /// since packet-size chunking doesn't apply to a continuous stream. On
+    /// a datagram transport (UDP, Unix datagram) it's chunked into sends no
+    /// larger than `max_packet_size` without ever splitting a line across
+    /// two datagrams.
+    fn send(&mut self) -> io::Result<()> {
+        let data = self.export();
+        if self.transport.is_stream() {
+            if !data.is_empty() {
+                self.transport.send(&data)?;
+            }
+            return Ok(());
+        }
+        let mut chunk_start = 0usize;
+        let mut chunk_end = 0usize;
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            if chunk_end > chunk_start && chunk_end - chunk_start + line.len() > self.max_packet_size
+            {
+                self.transport.send(&data[chunk_start..chunk_end])?;
+                chunk_start = chunk_end;
+            }
+            chunk_end += line.len();
+        }
+        if chunk_end > chunk_start {
+            self.transport.send(&data[chunk_start..chunk_end])?;
         }
         Ok(())
     }
 
-    pub fn run(self) {
+    /// Runs the export loop until `shutdown` fires, then sends one last
+    /// batch so the final interval's metrics aren't lost.
+    pub(crate) fn run(mut self, shutdown: mpsc::Receiver<()>) {
         loop {
-            std::thread::sleep(self.interval);
-            self.send().unwrap();
+            match shutdown.recv_timeout(self.interval) {
+                Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    if let Err(e) = self.send() {
+                        log::error!("Statsd exporter failed to send metrics: {}", e);
+                    }
+                    return;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if let Err(e) = self.send() {
+                        log::error!("Statsd exporter failed to send metrics: {}", e);
+                    }
+                }
+            }
         }
     }
 }
@@ -79,8 +338,9 @@ mod tests {
     use super::*;
 
     use std::mem::MaybeUninit;
-    use std::net::Ipv6Addr;
+    use std::net::{Ipv6Addr, UdpSocket};
 
+    use bytes::Buf;
     use metrics::{Key, Recorder};
 
     /// Return exporter and the socket to which it sends data.
@@ -88,22 +348,18 @@ mod tests {
         let recorder = PlainRecorder::new();
         let recv_socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
         let send_socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
-        let exporter = StatsdExporter::new(
-            send_socket,
-            recv_socket.local_addr().unwrap(),
-            Duration::from_secs(1),
-            recorder,
-        );
+        let transport = Transport::udp(send_socket, recv_socket.local_addr().unwrap());
+        let exporter = StatsdExporter::new(transport, Duration::from_secs(1), recorder);
         (exporter, recv_socket)
     }
 
     #[test]
     fn test_export_counter() {
-        let (exporter, recv_socket) = statsd_exporter();
+        let (mut exporter, recv_socket) = statsd_exporter();
 
         let c0 = exporter
             .recorder
-            .register_counter(Key::from_name("spam"), None);
+            .register_counter(Key::from_name("spam"), None, None);
         let out = exporter.export();
         assert_eq!(out.bytes(), b"spam:0|c\n");
 
@@ -114,11 +370,11 @@ mod tests {
 
     #[test]
     fn test_export_gauge() {
-        let (exporter, recv_socket) = statsd_exporter();
+        let (mut exporter, recv_socket) = statsd_exporter();
 
         let g0 = exporter
             .recorder
-            .register_gauge(Key::from_name("spam"), None);
+            .register_gauge(Key::from_name("spam"), None, None);
         let out = exporter.export();
         assert_eq!(out.bytes(), b"spam:0|g\n");
 
@@ -132,18 +388,271 @@ mod tests {
     }
 
     #[test]
-    fn test_send() {
+    fn test_export_histogram_raw() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+
+        let h0 = exporter
+            .recorder
+            .register_histogram(Key::from_name("spam"), None, None);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"");
+
+        exporter.recorder.record_histogram(h0, 3);
+        exporter.recorder.record_histogram(h0, 7);
+        let out = exporter.export();
+        let mut lines: Vec<&str> = std::str::from_utf8(out.bytes()).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["spam:3|ms", "spam:7|ms"]);
+
+        // No new samples recorded since the last export: nothing is
+        // re-emitted, even though `read_histogram` still returns the full
+        // cumulative history.
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"");
+
+        // Only the newly recorded sample is emitted, not the whole history.
+        exporter.recorder.record_histogram(h0, 9);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"spam:9|ms\n");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_export_histogram_count_unit() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+
+        let h0 = exporter.recorder.register_histogram(
+            Key::from_name("spam"),
+            Some(metrics::Unit::Count),
+            None,
+        );
+        exporter.recorder.record_histogram(h0, 3);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"spam:3|c\n");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_export_histogram_summary() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+        exporter.histogram_summary(vec![0.5, 0.99]);
+
+        let h0 = exporter
+            .recorder
+            .register_histogram(Key::from_name("spam"), None, None);
+        for value in [1, 2, 3, 4, 5] {
+            exporter.recorder.record_histogram(h0, value);
+        }
+        let out = exporter.export();
+        let mut lines: Vec<&str> = std::str::from_utf8(out.bytes()).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "spam.count:5|g",
+                "spam.max:5|g",
+                "spam.mean:3|g",
+                "spam.min:1|g",
+                "spam.p50:3|g",
+                "spam.p99:5|g",
+            ]
+        );
+
+        // No new samples since the last export: the summary isn't
+        // recomputed over the same all-time history again.
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_export_dogstatsd_tags() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+        exporter.dogstatsd(true);
+        exporter.constant_tags(vec![("env".to_string(), "prod".to_string())]);
+
+        let c0 = exporter.recorder.register_counter(
+            Key::from_parts("spam", vec![metrics::Label::new("flavor", "ham")]),
+            None,
+            None,
+        );
+        exporter.recorder.increment_counter(c0, 1);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"spam:1|c|#env:prod,flavor:ham\n");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_export_prefix() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+        exporter.prefix("myapp".to_string());
+
+        let c0 = exporter
+            .recorder
+            .register_counter(Key::from_name("spam"), None, None);
+        exporter.recorder.increment_counter(c0, 1);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"myapp.spam:1|c\n");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_export_skips_unchanged_values() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+
+        let c0 = exporter
+            .recorder
+            .register_counter(Key::from_name("spam"), None, None);
+        let g0 = exporter
+            .recorder
+            .register_gauge(Key::from_name("eggs"), None, None);
+        exporter.recorder.update_gauge(g0, 11.0);
+
+        // First export always reports every known metric.
+        let out = exporter.export();
+        let mut lines: Vec<&str> = std::str::from_utf8(out.bytes()).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["eggs:11|g", "spam:0|c"]);
+
+        // Nothing changed since the last export, so the second is empty.
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"");
+
+        // Only the counter changed; the gauge stays quiet and the counter
+        // reports the delta, not the cumulative total.
+        exporter.recorder.increment_counter(c0, 5);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"spam:5|c\n");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_send_chunks_on_max_packet_size() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+        exporter.max_packet_size(12);
+
+        exporter
+            .recorder
+            .register_counter(Key::from_name("spam"), None, None);
+        exporter
+            .recorder
+            .register_counter(Key::from_name("ham"), None, None);
+        exporter.send().unwrap();
+
+        let mut datagrams = Vec::new();
+        for _ in 0..2 {
+            let mut buf = [0u8; 64];
+            let count = recv_socket.recv(&mut buf).unwrap();
+            assert!(count <= 12);
+            datagrams.push(String::from_utf8(buf[..count].to_vec()).unwrap());
+        }
+        datagrams.sort();
+        assert_eq!(datagrams, ["ham:0|c\n", "spam:0|c\n"]);
+    }
+
+    #[test]
+    fn test_export_counter_sample_rate() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+        exporter.sample_rate(0.5);
+
+        let c0 = exporter
+            .recorder
+            .register_counter(Key::from_name("spam"), None, None);
+        exporter.recorder.increment_counter(c0, 10);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"spam:5|c|@0.5\n");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_export_counter_sample_rate_carries_remainder_forward() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+        exporter.sample_rate(0.1);
+
+        let c0 = exporter
+            .recorder
+            .register_counter(Key::from_name("spam"), None, None);
+        // First export always reports the metric's baseline value.
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"spam:0|c|@0.1\n");
+
+        // Each individual +1 rounds its delta down to a zero-scaled count
+        // and is suppressed, but the unreported amount is carried forward
+        // rather than dropped: once the accumulated delta reaches 5, it
+        // finally rounds up to a reported count of 1.
+        for _ in 0..4 {
+            exporter.recorder.increment_counter(c0, 1);
+            let out = exporter.export();
+            assert_eq!(out.bytes(), b"");
+        }
+        exporter.recorder.increment_counter(c0, 1);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"spam:1|c|@0.1\n");
+
+        // The carried amount was consumed by that report, so the next
+        // single increment goes back to accumulating from zero.
+        exporter.recorder.increment_counter(c0, 1);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_histogram_sample_rate_zero_drops_all_samples() {
+        let (mut exporter, recv_socket) = statsd_exporter();
+        exporter.sample_rate(0.0);
+
+        let h0 = exporter
+            .recorder
+            .register_histogram(Key::from_name("spam"), None, None);
+        exporter.recorder.record_histogram(h0, 3);
+        exporter.recorder.record_histogram(h0, 7);
+        let out = exporter.export();
+        assert_eq!(out.bytes(), b"");
+
+        let _ = recv_socket;
+    }
+
+    #[test]
+    fn test_run_flushes_on_shutdown() {
         let (exporter, recv_socket) = statsd_exporter();
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+
+        let c0 = exporter
+            .recorder
+            .register_counter(Key::from_name("spam"), None, None);
+        exporter.recorder.increment_counter(c0, 1);
+
+        let handle = std::thread::spawn(move || exporter.run(shutdown_rx));
+        shutdown_tx.send(()).unwrap();
+        handle.join().unwrap();
+
+        let mut buf = [0u8; 64];
+        let count = recv_socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..count], b"spam:1|c\n");
+    }
+
+    #[test]
+    fn test_send() {
+        let (mut exporter, recv_socket) = statsd_exporter();
 
         let c0 = exporter
             .recorder
-            .register_counter(Key::from_name("spam"), None);
+            .register_counter(Key::from_name("spam"), None, None);
         let c1 = exporter
             .recorder
-            .register_counter(Key::from_name("ham"), None);
+            .register_counter(Key::from_name("ham"), None, None);
         let g0 = exporter
             .recorder
-            .register_gauge(Key::from_name("eggs"), None);
+            .register_gauge(Key::from_name("eggs"), None, None);
         exporter.recorder.increment_counter(c0, 3);
         exporter.recorder.increment_counter(c1, 7);
         exporter.recorder.update_gauge(g0, 11.0);
@@ -0,0 +1,182 @@
+//! Pluggable transports for writing out rendered statsd lines.
+
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+
+/// Where the exporter actually writes its rendered metric lines.
+///
+/// UDP and Unix datagram sockets are message-oriented, so the exporter is
+/// free to chunk a batch into several `send`s; TCP is a continuous stream,
+/// so chunking by packet size doesn't apply there.
+#[derive(Debug)]
+pub(crate) enum Transport {
+    Udp {
+        socket: UdpSocket,
+        peer_addr: SocketAddr,
+    },
+    Tcp {
+        addr: SocketAddr,
+        stream: Option<TcpStream>,
+    },
+    UnixDatagram {
+        socket: UnixDatagram,
+    },
+}
+
+impl Transport {
+    pub(crate) fn udp(socket: UdpSocket, peer_addr: SocketAddr) -> Self {
+        Transport::Udp { socket, peer_addr }
+    }
+
+    /// Connects lazily on the first `send`.
+    pub(crate) fn tcp(addr: SocketAddr) -> Self {
+        Transport::Tcp { addr, stream: None }
+    }
+
+    pub(crate) fn unix_datagram(path: PathBuf) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Transport::UnixDatagram { socket })
+    }
+
+    /// Whether this transport is a continuous stream rather than a
+    /// message-oriented datagram socket.
+    pub(crate) fn is_stream(&self) -> bool {
+        matches!(self, Transport::Tcp { .. })
+    }
+
+    /// Send `data` as a single unit. On a TCP transport, a failed send
+    /// drops the stream so the next call reconnects instead of repeatedly
+    /// failing against a dead socket.
+    pub(crate) fn send(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Transport::Udp { socket, peer_addr } => socket.send_to(data, *peer_addr),
+            Transport::Tcp { addr, stream } => {
+                if stream.is_none() {
+                    *stream = Some(TcpStream::connect(*addr)?);
+                }
+                let result = stream
+                    .as_mut()
+                    .expect("just connected above")
+                    .write_all(data)
+                    .map(|()| data.len());
+                if result.is_err() {
+                    *stream = None;
+                }
+                result
+            }
+            Transport::UnixDatagram { socket } => socket.send(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::net::{Ipv6Addr, TcpListener};
+    use std::time::Duration;
+
+    #[test]
+    fn test_udp_send() {
+        let recv_socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        let send_socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        let mut transport = Transport::udp(send_socket, recv_socket.local_addr().unwrap());
+
+        transport.send(b"spam:1|c\n").unwrap();
+        let mut buf = [0u8; 64];
+        let count = recv_socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..count], b"spam:1|c\n");
+        assert!(!transport.is_stream());
+    }
+
+    #[test]
+    fn test_unix_datagram_send() {
+        let dir = std::env::temp_dir().join(format!("statsd-test-{:?}", std::thread::current().id()));
+        let _ = std::fs::remove_file(&dir);
+        let recv_socket = UnixDatagram::bind(&dir).unwrap();
+        let mut transport = Transport::unix_datagram(dir.clone()).unwrap();
+
+        transport.send(b"spam:1|c\n").unwrap();
+        let mut buf = [0u8; 64];
+        let count = recv_socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..count], b"spam:1|c\n");
+
+        let _ = std::fs::remove_file(&dir);
+    }
+
+    #[test]
+    fn test_tcp_is_stream() {
+        let transport = Transport::tcp(SocketAddr::from((Ipv6Addr::LOCALHOST, 0)));
+        assert!(transport.is_stream());
+    }
+
+    #[test]
+    fn test_tcp_send() {
+        let listener = TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut transport = Transport::tcp(addr);
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 64];
+            let count = stream.read(&mut buf).unwrap();
+            buf[..count].to_vec()
+        });
+
+        transport.send(b"spam:1|c\n").unwrap();
+        assert_eq!(server.join().unwrap(), b"spam:1|c\n");
+        assert!(transport.is_stream());
+    }
+
+    #[test]
+    fn test_tcp_reconnects_after_dropped_connection() {
+        let listener = TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut transport = Transport::tcp(addr);
+        let (closed_tx, closed_rx) = std::sync::mpsc::channel();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 64];
+            let count = stream.read(&mut buf).unwrap();
+            let first = buf[..count].to_vec();
+            // Force an abrupt close (RST) instead of a graceful FIN, so the
+            // client's next write observes the broken connection instead of
+            // racing a delayed close notification.
+            stream.set_linger(Some(Duration::from_secs(0))).unwrap();
+            drop(stream);
+            closed_tx.send(()).unwrap();
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let count = stream.read(&mut buf).unwrap();
+            let second = buf[..count].to_vec();
+            (first, second)
+        });
+
+        transport.send(b"spam:1|c\n").unwrap();
+        closed_rx.recv().unwrap();
+
+        // The server has closed its side of the connection; the write
+        // below should fail and drop the stale stream rather than hang.
+        let mut reconnected = false;
+        for _ in 0..50 {
+            if transport.send(b"spam:2|c\n").is_ok() {
+                reconnected = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(
+            reconnected,
+            "transport should reconnect after the server closed the connection"
+        );
+
+        let (first, second) = server.join().unwrap();
+        assert_eq!(first, b"spam:1|c\n");
+        assert_eq!(second, b"spam:2|c\n");
+    }
+}